@@ -0,0 +1,137 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! `#[derive(Packet)]`, re-exported from `dcc` behind the `derive`
+//! feature.
+//!
+//! Mark exactly one field `#[dcc(address)]` (its type must be
+//! [`dcc::packets::AddressMode`](../dcc/packets/enum.AddressMode.html))
+//! and every remaining field `#[dcc(bits = N)]`, in the order they should
+//! be packed onto the wire, MSB-first. The derive generates a
+//! `serialize` method that packs the bit fields into instruction bytes,
+//! then hands them to [`dcc::packets::serialize_addressed`] to assemble
+//! the address bytes and trailing XOR error-detection byte.
+//!
+//! ```ignore
+//! #[derive(dcc::packets::Packet)]
+//! struct SetBrightness {
+//!     #[dcc(address)]
+//!     address: dcc::packets::AddressMode,
+//!     #[dcc(bits = 8)]
+//!     brightness: u8,
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+/// See the crate-level documentation.
+#[proc_macro_derive(Packet, attributes(dcc))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => panic!("#[derive(Packet)] only supports structs with named fields"),
+        },
+        _ => panic!("#[derive(Packet)] only supports structs"),
+    };
+
+    let mut address_field = None;
+    let mut bit_fields = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let mut is_address = false;
+        let mut bits = None;
+
+        for attr in &field.attrs {
+            if !attr.path().is_ident("dcc") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("address") {
+                    is_address = true;
+                } else if meta.path.is_ident("bits") {
+                    let width: syn::LitInt = meta.value()?.parse()?;
+                    bits = Some(width.base10_parse::<u32>()?);
+                }
+                Ok(())
+            })
+            .unwrap_or_else(|e| panic!("invalid #[dcc(...)] attribute on `{ident}`: {e}"));
+        }
+
+        if is_address {
+            address_field = Some(ident.clone());
+        } else if let Some(bits) = bits {
+            bit_fields.push((ident.clone(), bits));
+        }
+    }
+
+    let address_field = address_field
+        .unwrap_or_else(|| panic!("#[derive(Packet)] on `{name}` requires one #[dcc(address)] field"));
+
+    // the field widths are literal `#[dcc(bits = N)]` attributes, so the
+    // packed byte length is known at macro-expansion time; a struct whose
+    // fields don't fit in the instruction buffer is a structural error in
+    // the derive input, not something that can vary at runtime
+    let total_bits: u32 = bit_fields.iter().map(|(_, bits)| *bits).sum();
+    let byte_len = total_bits.div_ceil(8) as usize;
+    if byte_len > 4 {
+        panic!(
+            "#[derive(Packet)] on `{name}` packs {total_bits} bits ({byte_len} bytes), \
+             but the instruction buffer only holds 4 bytes"
+        );
+    }
+
+    let widths = bit_fields.iter().map(|(_, bits)| *bits);
+    let values = bit_fields.iter().map(|(ident, _)| quote! { self.#ident as u64 });
+
+    quote! {
+        impl ::dcc::packets::Packet for #name {}
+
+        impl #name {
+            /// Serialize the packet into the provided buffer.
+            ///
+            /// Generated by `#[derive(Packet)]`. Returns
+            /// [`dcc::Error::TooLong`](::dcc::Error::TooLong) if the
+            /// address field is [`AddressMode::Long`](::dcc::packets::AddressMode::Long)
+            /// and doesn't leave enough room for this packet's instruction
+            /// bytes; see [`dcc::packets::serialize_addressed`](::dcc::packets::serialize_addressed).
+            pub fn serialize(
+                &self,
+                buf: &mut ::dcc::packets::SerializeBuffer,
+            ) -> ::dcc::packets::Result<usize> {
+                const WIDTHS: &[u32] = &[#(#widths),*];
+                const TOTAL_BITS: u32 = #total_bits;
+                const BYTE_LEN: usize = #byte_len;
+                let values: &[u64] = &[#(#values),*];
+
+                // packed in a u64 (rather than the 32-bit instruction this
+                // ultimately becomes) so that a single field consuming the
+                // entire 32-bit budget doesn't overflow `1 << width`
+                let mut packed: u64 = 0;
+                for (value, width) in values.iter().zip(WIDTHS) {
+                    packed = (packed << width) | (value & ((1u64 << width) - 1));
+                }
+                packed <<= (BYTE_LEN as u32) * 8 - TOTAL_BITS;
+
+                let mut instruction = [0u8; 4];
+                for (i, byte) in instruction.iter_mut().enumerate().take(BYTE_LEN) {
+                    *byte = (packed >> ((BYTE_LEN - 1 - i) as u32 * 8)) as u8;
+                }
+
+                ::dcc::packets::serialize_addressed::<Self>(
+                    self.#address_field,
+                    &instruction[..BYTE_LEN],
+                    buf,
+                )
+            }
+        }
+    }
+    .into()
+}
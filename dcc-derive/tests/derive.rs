@@ -0,0 +1,52 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! End-to-end test of the generated `serialize` method, exercising the
+//! derive macro the way a downstream vendor-packet definition would.
+
+use bitvec::prelude::*;
+use dcc::packets::{AddressMode, SerializeBuffer};
+
+#[derive(dcc::packets::Packet)]
+struct SetBrightness {
+    #[dcc(address)]
+    address: AddressMode,
+    #[dcc(bits = 8)]
+    brightness: u8,
+}
+
+#[test]
+fn serializes_generated_instruction_byte() {
+    let pkt = SetBrightness {
+        address: AddressMode::Short(3),
+        brightness: 0x42,
+    };
+    let mut buf = SerializeBuffer::default();
+    let len = pkt.serialize(&mut buf).unwrap();
+
+    // address(1) + instruction(1) + ecc(1) = 3 data bytes
+    let expected_arr = [0xff_u8, 0xfe, 0x03, 0x21, 0x10, 0x60];
+    let mut expected = SerializeBuffer::default();
+    expected[..43].copy_from_bitslice(&expected_arr.view_bits::<Msb0>()[..43]);
+    assert_eq!(len, 15 + 3 * 9 + 1);
+    assert_eq!(buf[..len], expected[..43]);
+}
+
+#[test]
+fn long_address_leaves_no_room_for_a_four_byte_instruction() {
+    #[derive(dcc::packets::Packet)]
+    struct WideInstruction {
+        #[dcc(address)]
+        address: AddressMode,
+        #[dcc(bits = 32)]
+        payload: u32,
+    }
+
+    let pkt = WideInstruction {
+        address: AddressMode::Long(300),
+        payload: 0,
+    };
+    let mut buf = SerializeBuffer::default();
+    assert_eq!(pkt.serialize(&mut buf).err(), Some(dcc::Error::TooLong));
+}
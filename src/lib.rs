@@ -0,0 +1,36 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! `dcc-rs` is a `no_std` crate for constructing and decoding NMRA Digital
+//! Command Control (DCC) packets.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod packets;
+#[cfg(feature = "embedded-hal")]
+pub mod transmit;
+
+/// Errors that can occur while building, serializing, or decoding a
+/// [`packets::Packet`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The packet, once serialized, would not fit into the provided buffer.
+    TooLong,
+    /// The provided address is outside of the range allowed for the
+    /// selected addressing mode.
+    InvalidAddress,
+    /// The provided speed is outside of the range allowed for the
+    /// selected speed-step mode.
+    InvalidSpeed,
+    /// The trailing error-detection byte did not match the XOR of the
+    /// preceding data bytes.
+    BadChecksum,
+    /// The provided function index is outside of the range carried by
+    /// the selected function group, or a CV bit index is outside 0-7.
+    InvalidFunction,
+    /// The provided Configuration Variable number is outside of the
+    /// 1-1024 range a decoder can address, or was never set.
+    InvalidCv,
+}
@@ -9,7 +9,7 @@
 
 use core::ops::Not;
 
-use super::{Address, Packet, Preamble, Result, SerializeBuffer};
+use super::{AddressMode, Packet, Preamble, Result, SerializeBuffer};
 use crate::Error;
 use bitvec::prelude::*;
 
@@ -61,7 +61,7 @@ impl Not for Direction {
 ///  1 1111 | speed 28 (0x1f)
 /// ```
 pub struct SpeedAndDirection {
-    address: Address,
+    address: AddressMode,
     instruction: u8,
 }
 
@@ -76,37 +76,35 @@ impl SpeedAndDirection {
 
     /// Serialize the packed into the provided buffer
     pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
-        <Self as Packet>::serialize(
-            &[
-                self.address,
-                self.instruction,
-                self.address ^ self.instruction,
-            ],
-            buf,
-        )
+        let (address_bytes, address_len) = self.address.to_bytes();
+
+        let mut data = [0u8; 4];
+        data[..address_len].copy_from_slice(&address_bytes[..address_len]);
+        data[address_len] = self.instruction;
+        let ecc = data[..=address_len].iter().fold(0, |acc, b| acc ^ b);
+        data[address_len + 1] = ecc;
+
+        <Self as Packet>::serialize(&data[..address_len + 2], buf)
     }
 }
 
 /// Builder used to construct a SpeedAndDirection packet
 #[derive(Default)]
 pub struct SpeedAndDirectionBuilder {
-    address: Option<Address>,
+    address: Option<AddressMode>,
     speed: Option<u8>,
     e_stop: bool,
     direction: Option<Direction>,
 }
 
 impl SpeedAndDirectionBuilder {
-    /// Sets the address. In short mode, this must be between 1
-    /// and 126. Returns [`Error::InvalidAddress`] if the provided address
-    /// is outside of this range.
-    pub fn address(&mut self, address: Address) -> Result<&mut Self> {
-        if address == 0 || address > 0x7f {
-            Err(Error::InvalidAddress)
-        } else {
-            self.address = Some(address);
-            Ok(self)
-        }
+    /// Sets the address, which may be a short address (1-127) or a
+    /// long/extended address (128-10239). Returns
+    /// [`Error::InvalidAddress`] if `address` is outside the range its
+    /// mode allows.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
     }
 
     /// Sets the speed. In short mode the speed has to be between 0 and
@@ -144,7 +142,7 @@ impl SpeedAndDirectionBuilder {
     /// * `address = 3`
     /// * `headlight = false`
     pub fn build(&mut self) -> SpeedAndDirection {
-        let address: Address = self.address.unwrap_or(3);
+        let address = self.address.unwrap_or(AddressMode::Short(3));
         // add the weird offset to the speed
         let speed = match self.speed {
             Some(0) | None => 0,
@@ -275,11 +273,11 @@ mod test {
     #[test]
     fn make_speed_and_direction() -> Result<()> {
         let pkt = SpeedAndDirection::builder()
-            .address(35)?
+            .address(AddressMode::Short(35))?
             .speed(14)?
             .direction(Direction::Forward)
             .build();
-        assert_eq!(pkt.address, 35);
+        assert_eq!(pkt.address, AddressMode::Short(35));
         let expected = 0b0111_1000;
         eprintln!("Got instruction: {:08b}", pkt.instruction);
         eprintln!("Expected:        {expected:08b}");
@@ -291,7 +289,7 @@ mod test {
     #[test]
     fn serialize_speed_and_direction() -> Result<()> {
         let pkt = SpeedAndDirection::builder()
-            .address(35)?
+            .address(AddressMode::Short(35))?
             .speed(14)?
             .direction(Direction::Forward)
             .build();
@@ -321,6 +319,51 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn serialize_speed_and_direction_long_address() -> Result<()> {
+        let pkt = SpeedAndDirection::builder()
+            .address(AddressMode::Long(300))?
+            .speed(14)?
+            .direction(Direction::Forward)
+            .build();
+        let mut buf = SerializeBuffer::default();
+        let len = pkt.serialize(&mut buf)?;
+        // the long address adds a fourth data byte, so the total length
+        // grows from 43 to 52 bits
+        #[allow(clippy::unusual_byte_groupings)]
+        let expected_arr = [
+            0xff_u8,      // preamble
+            0b1111_1110,  // preamble + start
+            0xc1,         // address high: 11AAAAAA marker + top 6 bits
+            0b0_0010110,  // start + address low[..7]
+            0b0_0_011110, // address low[7] + start + instr[..6]
+            0b00_0_10010, // instr[6..] + start + ecc[..5]
+            0b101_1_0000, // ecc[5..] + stop + 4 zeroes
+        ];
+        let mut expected = SerializeBuffer::default();
+        expected[..52]
+            .copy_from_bitslice(&expected_arr.view_bits::<Msb0>()[..52]);
+        assert_eq!(len, 52);
+        assert_eq!(buf[..len], expected[..52]);
+        Ok(())
+    }
+
+    #[test]
+    fn long_address_out_of_range_is_rejected() {
+        assert_eq!(
+            SpeedAndDirection::builder()
+                .address(AddressMode::Long(10240))
+                .err(),
+            Some(Error::InvalidAddress)
+        );
+        assert_eq!(
+            SpeedAndDirection::builder()
+                .address(AddressMode::Long(127))
+                .err(),
+            Some(Error::InvalidAddress)
+        );
+    }
+
     #[test]
     fn serialize_reset_packet() -> Result<()> {
         let pkt = Reset;
@@ -0,0 +1,283 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Recovers typed [`DecodedPacket`]s from a bitstream sampled off the
+//! track, the inverse of [`super::Packet::serialize`].
+
+use super::{AddressMode, Direction, MAX_BITS, Result};
+use crate::Error;
+use bitvec::prelude::*;
+
+/// The maximum number of data bytes (address/instruction/data bytes plus
+/// the trailing error-detection byte) that [`decode`] will ever recover,
+/// derived from the data-byte budget implied by [`MAX_BITS`].
+const MAX_DATA_BYTES: usize = (MAX_BITS - 16) / 9;
+
+/// The raw data bytes of a packet, in the order they appeared on the
+/// rails, with the trailing error-detection byte already validated and
+/// stripped off.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub struct RawBytes {
+    bytes: [u8; MAX_DATA_BYTES],
+    len: usize,
+}
+
+impl RawBytes {
+    /// The decoded data bytes, with the error-detection byte removed.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// A packet recovered from a sampled bitstream by [`decode`].
+///
+/// This covers the `Reset`, `Idle` and `SpeedAndDirection` packets of
+/// [`super::baseline`], falling back to [`DecodedPacket::Unknown`] for any
+/// other address/instruction layout. There is no `BroadcastStop` variant:
+/// a broadcast stop decodes as `SpeedAndDirection` with address
+/// `AddressMode::Short(0)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum DecodedPacket {
+    /// A `Reset` packet; see [`super::Reset`].
+    Reset,
+    /// An `Idle` packet; see [`super::Idle`].
+    Idle,
+    /// A `SpeedAndDirection` packet; see [`super::SpeedAndDirection`].
+    SpeedAndDirection {
+        /// The decoder address the packet was sent to.
+        address: AddressMode,
+        /// The commanded direction.
+        direction: Direction,
+        /// The commanded speed, already adjusted for the e-stop offset,
+        /// or `None` if this is an e-stop packet.
+        speed: Option<u8>,
+        /// Whether this packet is commanding an emergency stop.
+        e_stop: bool,
+    },
+    /// A packet whose data bytes were recovered and checksum-verified,
+    /// but whose address/instruction layout is not yet recognized.
+    Unknown(RawBytes),
+}
+
+/// Scans `bits` for a DCC packet and decodes it into a [`DecodedPacket`].
+///
+/// This performs the inverse of [`super::Packet::serialize`]: it looks for
+/// a run of at least 10 consecutive `1` bits (the minimum a receiver is
+/// required to recognize as a preamble), then reads `0`-prefixed data
+/// bytes until it finds the `1` stop bit at a byte boundary. The final
+/// data byte is treated as an XOR error-detection byte and checked
+/// against the bytes preceding it.
+///
+/// # Returns
+///
+/// The decoded packet and the bit offset immediately following its stop
+/// bit, so callers can resume scanning `bits` for the next packet.
+pub fn decode(bits: &BitSlice<u8, Msb0>) -> Result<(DecodedPacket, usize)> {
+    let (bytes, end) = decode_bytes(bits)?;
+    Ok((dispatch(&bytes), end))
+}
+
+/// Scans `bits` for a preamble, start bits, and checksum-verified data
+/// bytes, without interpreting the address/instruction layout.
+fn decode_bytes(bits: &BitSlice<u8, Msb0>) -> Result<(RawBytes, usize)> {
+    // (1) find a run of at least 10 consecutive `1` bits, anywhere in
+    // `bits`. Controllers send a longer preamble than the 10-bit
+    // minimum (see `Packet::serialize`), so once we've seen 10 ones we
+    // keep consuming any further ones before looking for the `0` start
+    // bit that ends the preamble.
+    let mut ones = 0;
+    let mut pos = 0;
+    while pos < bits.len() {
+        if bits[pos] {
+            ones += 1;
+            pos += 1;
+            if ones >= 10 {
+                while pos < bits.len() && bits[pos] {
+                    pos += 1;
+                }
+                break;
+            }
+        } else {
+            ones = 0;
+            pos += 1;
+        }
+    }
+    if ones < 10 {
+        return Err(Error::BadChecksum);
+    }
+
+    // (2)/(3) alternate a `0` start bit and 8 data bits, until we hit the
+    // `1` stop bit at a byte boundary instead of a start bit
+    let mut bytes = [0u8; MAX_DATA_BYTES];
+    let mut len = 0;
+    loop {
+        if pos >= bits.len() {
+            return Err(Error::BadChecksum);
+        }
+        if bits[pos] {
+            // stop bit
+            pos += 1;
+            break;
+        }
+        pos += 1; // consume the `0` start bit
+
+        if len >= MAX_DATA_BYTES || pos + 8 > bits.len() {
+            return Err(Error::BadChecksum);
+        }
+        let mut byte = 0u8;
+        for bit in &bits[pos..pos + 8] {
+            byte = (byte << 1) | (*bit as u8);
+        }
+        bytes[len] = byte;
+        len += 1;
+        pos += 8;
+    }
+
+    if len < 2 {
+        return Err(Error::BadChecksum);
+    }
+
+    // (4) the last byte read is the XOR error-detection byte
+    let ecc = bytes[len - 1];
+    let checksum = bytes[..len - 1].iter().fold(0u8, |acc, b| acc ^ b);
+    if checksum != ecc {
+        return Err(Error::BadChecksum);
+    }
+
+    Ok((
+        RawBytes {
+            bytes,
+            len: len - 1,
+        },
+        pos,
+    ))
+}
+
+/// Reconstructs a concrete packet type from its checksum-verified data
+/// bytes, falling back to [`DecodedPacket::Unknown`].
+fn dispatch(raw: &RawBytes) -> DecodedPacket {
+    match raw.as_slice() {
+        [0x00, 0x00] => DecodedPacket::Reset,
+        [0xff, 0x00] => DecodedPacket::Idle,
+        [address, instruction] if instruction & 0b1100_0000 == 0b0100_0000 => {
+            speed_and_direction(AddressMode::Short(*address), *instruction)
+        }
+        [addr_hi, addr_lo, instruction]
+            if addr_hi & 0b1100_0000 == 0b1100_0000 && instruction & 0b1100_0000 == 0b0100_0000 =>
+        {
+            // inverse of `AddressMode::Long::to_bytes`: the `11AAAAAA`
+            // marker in the high byte is stripped back off
+            let address = (((addr_hi & 0x3f) as u16) << 8) | *addr_lo as u16;
+            speed_and_direction(AddressMode::Long(address), *instruction)
+        }
+        _ => DecodedPacket::Unknown(*raw),
+    }
+}
+
+/// Reconstructs the direction/speed/e-stop fields of a `SpeedAndDirection`
+/// instruction byte, the inverse of [`super::SpeedAndDirectionBuilder::build`].
+fn speed_and_direction(address: AddressMode, instruction: u8) -> DecodedPacket {
+    let direction = if instruction & 0b0010_0000 != 0 {
+        Direction::Forward
+    } else {
+        Direction::Backward
+    };
+    let e_stop = instruction & 0b0000_1111 == 0b0001;
+    // speed bits are ordered `04321`: bit 0 (the LSB) is stashed in
+    // bit 4 of the instruction byte, with `4321` directly below it
+    let speed_bits = ((instruction & 0x0f) << 1) | ((instruction >> 4) & 0x01);
+    // `SpeedAndDirectionBuilder::build` offsets a non-zero speed by 3
+    // before packing it into `speed_bits`
+    let speed = match (e_stop, speed_bits) {
+        (true, _) => None,
+        (false, 0) => Some(0),
+        (false, s) => Some(s.saturating_sub(3)),
+    };
+    DecodedPacket::SpeedAndDirection {
+        address,
+        direction,
+        speed,
+        e_stop,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packets::SerializeBuffer;
+
+    #[test]
+    fn decode_reset() -> Result<()> {
+        let mut buf = SerializeBuffer::default();
+        let len = crate::packets::Reset.serialize(&mut buf)?;
+        let (pkt, end) = decode(&buf[..len])?;
+        assert_eq!(pkt, DecodedPacket::Reset);
+        assert_eq!(end, len);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_idle() -> Result<()> {
+        let mut buf = SerializeBuffer::default();
+        let len = crate::packets::Idle.serialize(&mut buf)?;
+        let (pkt, _) = decode(&buf[..len])?;
+        assert_eq!(pkt, DecodedPacket::Idle);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_speed_and_direction() -> Result<()> {
+        let mut buf = SerializeBuffer::default();
+        let len = crate::packets::SpeedAndDirection::builder()
+            .address(AddressMode::Short(35))?
+            .speed(14)?
+            .direction(Direction::Forward)
+            .build()
+            .serialize(&mut buf)?;
+        let (pkt, _) = decode(&buf[..len])?;
+        assert_eq!(
+            pkt,
+            DecodedPacket::SpeedAndDirection {
+                address: AddressMode::Short(35),
+                direction: Direction::Forward,
+                speed: Some(14),
+                e_stop: false,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_speed_and_direction_long_address() -> Result<()> {
+        let mut buf = SerializeBuffer::default();
+        let len = crate::packets::SpeedAndDirection::builder()
+            .address(AddressMode::Long(300))?
+            .speed(14)?
+            .direction(Direction::Forward)
+            .build()
+            .serialize(&mut buf)?;
+        let (pkt, _) = decode(&buf[..len])?;
+        assert_eq!(
+            pkt,
+            DecodedPacket::SpeedAndDirection {
+                address: AddressMode::Long(300),
+                direction: Direction::Forward,
+                speed: Some(14),
+                e_stop: false,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_bad_checksum() -> Result<()> {
+        let mut buf = SerializeBuffer::default();
+        let len = crate::packets::Reset.serialize(&mut buf)?;
+        buf.set(16, true); // corrupt the address byte's top bit
+        assert_eq!(decode(&buf[..len]), Err(Error::BadChecksum));
+        Ok(())
+    }
+}
@@ -0,0 +1,482 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Packet types defined by the NMRA "Extended Packet Formats" standard:
+//! function control and the 128-speed-step advanced operations
+//! instruction.
+//!
+//! <https://www.nmra.org/sites/default/files/s-92-2001-2004.pdf>
+
+use super::{serialize_addressed, AddressMode, Direction, Packet, Result, SerializeBuffer};
+use crate::Error;
+
+/// Sets the state of functions F0 (the headlight) through F4, via the
+/// `100DFFFF` function group one instruction.
+pub struct FunctionGroup1 {
+    address: AddressMode,
+    instruction: u8,
+}
+
+impl Packet for FunctionGroup1 {}
+
+impl FunctionGroup1 {
+    /// Builder interface for `FunctionGroup1`.
+    pub fn builder() -> FunctionGroup1Builder {
+        FunctionGroup1Builder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        serialize_addressed::<Self>(self.address, &[self.instruction], buf)
+    }
+}
+
+/// Builder used to construct a [`FunctionGroup1`] packet
+#[derive(Default)]
+pub struct FunctionGroup1Builder {
+    address: Option<AddressMode>,
+    functions: [bool; 5],
+}
+
+impl FunctionGroup1Builder {
+    /// Sets the address this packet targets.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets function `index` (0 through 4, where 0 is the headlight, F0)
+    /// on or off. Returns [`Error::InvalidFunction`] if `index` is
+    /// outside of this range.
+    pub fn function(&mut self, index: u8, on: bool) -> Result<&mut Self> {
+        match self.functions.get_mut(index as usize) {
+            Some(slot) => {
+                *slot = on;
+                Ok(self)
+            }
+            None => Err(Error::InvalidFunction),
+        }
+    }
+
+    /// Build a [`FunctionGroup1`] packet using the provided values,
+    /// falling back to `address = 3` and all functions off.
+    pub fn build(&mut self) -> FunctionGroup1 {
+        let [f0, f1, f2, f3, f4] = self.functions;
+        let instruction = 0b1000_0000
+            | (f0 as u8) << 4
+            | (f4 as u8) << 3
+            | (f3 as u8) << 2
+            | (f2 as u8) << 1
+            | f1 as u8;
+
+        FunctionGroup1 {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            instruction,
+        }
+    }
+}
+
+/// Sets the state of functions F5 through F8, via the `1011FFFF` function
+/// group two instruction.
+pub struct FunctionGroup2High {
+    address: AddressMode,
+    instruction: u8,
+}
+
+impl Packet for FunctionGroup2High {}
+
+impl FunctionGroup2High {
+    /// Builder interface for `FunctionGroup2High`.
+    pub fn builder() -> FunctionGroup2HighBuilder {
+        FunctionGroup2HighBuilder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        serialize_addressed::<Self>(self.address, &[self.instruction], buf)
+    }
+}
+
+/// Builder used to construct a [`FunctionGroup2High`] packet
+#[derive(Default)]
+pub struct FunctionGroup2HighBuilder {
+    address: Option<AddressMode>,
+    functions: [bool; 4],
+}
+
+impl FunctionGroup2HighBuilder {
+    /// Sets the address this packet targets.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets function `index` (5 through 8) on or off. Returns
+    /// [`Error::InvalidFunction`] if `index` is outside of this range.
+    pub fn function(&mut self, index: u8, on: bool) -> Result<&mut Self> {
+        let slot = index
+            .checked_sub(5)
+            .and_then(|i| self.functions.get_mut(i as usize))
+            .ok_or(Error::InvalidFunction)?;
+        *slot = on;
+        Ok(self)
+    }
+
+    /// Build a [`FunctionGroup2High`] packet, falling back to
+    /// `address = 3` and all functions off.
+    pub fn build(&mut self) -> FunctionGroup2High {
+        let [f5, f6, f7, f8] = self.functions;
+        let instruction =
+            0b1011_0000 | (f8 as u8) << 3 | (f7 as u8) << 2 | (f6 as u8) << 1 | f5 as u8;
+
+        FunctionGroup2High {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            instruction,
+        }
+    }
+}
+
+/// Sets the state of functions F9 through F12, via the `1010FFFF`
+/// function group two instruction.
+pub struct FunctionGroup2Low {
+    address: AddressMode,
+    instruction: u8,
+}
+
+impl Packet for FunctionGroup2Low {}
+
+impl FunctionGroup2Low {
+    /// Builder interface for `FunctionGroup2Low`.
+    pub fn builder() -> FunctionGroup2LowBuilder {
+        FunctionGroup2LowBuilder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        serialize_addressed::<Self>(self.address, &[self.instruction], buf)
+    }
+}
+
+/// Builder used to construct a [`FunctionGroup2Low`] packet
+#[derive(Default)]
+pub struct FunctionGroup2LowBuilder {
+    address: Option<AddressMode>,
+    functions: [bool; 4],
+}
+
+impl FunctionGroup2LowBuilder {
+    /// Sets the address this packet targets.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets function `index` (9 through 12) on or off. Returns
+    /// [`Error::InvalidFunction`] if `index` is outside of this range.
+    pub fn function(&mut self, index: u8, on: bool) -> Result<&mut Self> {
+        let slot = index
+            .checked_sub(9)
+            .and_then(|i| self.functions.get_mut(i as usize))
+            .ok_or(Error::InvalidFunction)?;
+        *slot = on;
+        Ok(self)
+    }
+
+    /// Build a [`FunctionGroup2Low`] packet, falling back to
+    /// `address = 3` and all functions off.
+    pub fn build(&mut self) -> FunctionGroup2Low {
+        let [f9, f10, f11, f12] = self.functions;
+        let instruction =
+            0b1010_0000 | (f12 as u8) << 3 | (f11 as u8) << 2 | (f10 as u8) << 1 | f9 as u8;
+
+        FunctionGroup2Low {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            instruction,
+        }
+    }
+}
+
+/// Sets the state of functions F13 through F20, via the two-byte `1101
+/// 1110` (0xDE) Feature Expansion instruction followed by a byte carrying
+/// the full bitmap.
+pub struct FunctionGroup3 {
+    address: AddressMode,
+    bitmap: u8,
+}
+
+impl Packet for FunctionGroup3 {}
+
+impl FunctionGroup3 {
+    /// Builder interface for `FunctionGroup3`.
+    pub fn builder() -> FunctionGroup3Builder {
+        FunctionGroup3Builder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        serialize_addressed::<Self>(self.address, &[0xde, self.bitmap], buf)
+    }
+}
+
+/// Builder used to construct a [`FunctionGroup3`] packet
+#[derive(Default)]
+pub struct FunctionGroup3Builder {
+    address: Option<AddressMode>,
+    functions: [bool; 8],
+}
+
+impl FunctionGroup3Builder {
+    /// Sets the address this packet targets.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets function `index` (13 through 20) on or off. Returns
+    /// [`Error::InvalidFunction`] if `index` is outside of this range.
+    pub fn function(&mut self, index: u8, on: bool) -> Result<&mut Self> {
+        let slot = index
+            .checked_sub(13)
+            .and_then(|i| self.functions.get_mut(i as usize))
+            .ok_or(Error::InvalidFunction)?;
+        *slot = on;
+        Ok(self)
+    }
+
+    /// Build a [`FunctionGroup3`] packet, falling back to `address = 3`
+    /// and all functions off.
+    pub fn build(&mut self) -> FunctionGroup3 {
+        let bitmap = self
+            .functions
+            .iter()
+            .enumerate()
+            .fold(0u8, |acc, (i, on)| acc | (*on as u8) << i);
+
+        FunctionGroup3 {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            bitmap,
+        }
+    }
+}
+
+/// Sets the state of functions F21 through F28, via the two-byte `1101
+/// 1111` (0xDF) Feature Expansion instruction followed by a byte carrying
+/// the full bitmap.
+pub struct FunctionGroup4 {
+    address: AddressMode,
+    bitmap: u8,
+}
+
+impl Packet for FunctionGroup4 {}
+
+impl FunctionGroup4 {
+    /// Builder interface for `FunctionGroup4`.
+    pub fn builder() -> FunctionGroup4Builder {
+        FunctionGroup4Builder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        serialize_addressed::<Self>(self.address, &[0xdf, self.bitmap], buf)
+    }
+}
+
+/// Builder used to construct a [`FunctionGroup4`] packet
+#[derive(Default)]
+pub struct FunctionGroup4Builder {
+    address: Option<AddressMode>,
+    functions: [bool; 8],
+}
+
+impl FunctionGroup4Builder {
+    /// Sets the address this packet targets.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets function `index` (21 through 28) on or off. Returns
+    /// [`Error::InvalidFunction`] if `index` is outside of this range.
+    pub fn function(&mut self, index: u8, on: bool) -> Result<&mut Self> {
+        let slot = index
+            .checked_sub(21)
+            .and_then(|i| self.functions.get_mut(i as usize))
+            .ok_or(Error::InvalidFunction)?;
+        *slot = on;
+        Ok(self)
+    }
+
+    /// Build a [`FunctionGroup4`] packet, falling back to `address = 3`
+    /// and all functions off.
+    pub fn build(&mut self) -> FunctionGroup4 {
+        let bitmap = self
+            .functions
+            .iter()
+            .enumerate()
+            .fold(0u8, |acc, (i, on)| acc | (*on as u8) << i);
+
+        FunctionGroup4 {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            bitmap,
+        }
+    }
+}
+
+/// Commands a loco to move in the given direction at one of 126 speed
+/// steps, via the `0011 1111` advanced operations instruction.
+pub struct AdvancedOperations {
+    address: AddressMode,
+    instruction: u8,
+}
+
+impl Packet for AdvancedOperations {}
+
+impl AdvancedOperations {
+    /// Builder interface for `AdvancedOperations`.
+    pub fn builder() -> AdvancedOperationsBuilder {
+        AdvancedOperationsBuilder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        serialize_addressed::<Self>(self.address, &[0b0011_1111, self.instruction], buf)
+    }
+}
+
+/// Builder used to construct an [`AdvancedOperations`] packet
+#[derive(Default)]
+pub struct AdvancedOperationsBuilder {
+    address: Option<AddressMode>,
+    speed: Option<u8>,
+    e_stop: bool,
+    direction: Option<Direction>,
+}
+
+impl AdvancedOperationsBuilder {
+    /// Sets the address this packet targets.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets the speed, from 0 to 126. Returns [`Error::InvalidSpeed`] if
+    /// `speed` is outside of this range.
+    pub fn speed(&mut self, speed: u8) -> Result<&mut Self> {
+        if speed > 126 {
+            Err(Error::InvalidSpeed)
+        } else {
+            self.speed = Some(speed);
+            Ok(self)
+        }
+    }
+
+    /// Sets the direction
+    pub fn direction(&mut self, direction: Direction) -> &mut Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Sends the e-stop signal. Overrides any other set speed value
+    pub fn e_stop(&mut self, e_stop: bool) -> &mut Self {
+        self.e_stop = e_stop;
+        self
+    }
+
+    /// Build an [`AdvancedOperations`] packet, falling back to
+    /// `speed = 0`, `direction = Forward`, `address = 3`.
+    pub fn build(&mut self) -> AdvancedOperations {
+        // speed step 0 is stop, 1 is e-stop, 2..=127 are speed steps 1..=126
+        let speed = if self.e_stop {
+            1
+        } else {
+            match self.speed {
+                Some(0) | None => 0,
+                Some(speed) => speed + 1,
+            }
+        };
+
+        let mut instruction = speed & 0x7f;
+        if let Direction::Forward = self.direction.unwrap_or_default() {
+            instruction |= 0b1000_0000;
+        }
+
+        AdvancedOperations {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            instruction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn function_group_1_headlight_only() -> Result<()> {
+        let pkt = FunctionGroup1::builder()
+            .address(AddressMode::Short(3))?
+            .function(0, true)?
+            .build();
+        assert_eq!(pkt.instruction, 0b1001_0000);
+        Ok(())
+    }
+
+    #[test]
+    fn function_group_1_rejects_out_of_range_index() {
+        assert_eq!(
+            FunctionGroup1::builder().function(5, true).err(),
+            Some(Error::InvalidFunction)
+        );
+    }
+
+    #[test]
+    fn function_group_2_high_f5_and_f8() -> Result<()> {
+        let pkt = FunctionGroup2High::builder()
+            .function(5, true)?
+            .function(8, true)?
+            .build();
+        assert_eq!(pkt.instruction, 0b1011_1001);
+        Ok(())
+    }
+
+    #[test]
+    fn function_group_3_f13_and_f20() -> Result<()> {
+        let pkt = FunctionGroup3::builder()
+            .function(13, true)?
+            .function(20, true)?
+            .build();
+        assert_eq!(pkt.bitmap, 0b1000_0001);
+        Ok(())
+    }
+
+    #[test]
+    fn advanced_operations_speed_and_direction() -> Result<()> {
+        let pkt = AdvancedOperations::builder()
+            .speed(10)?
+            .direction(Direction::Forward)
+            .build();
+        assert_eq!(pkt.instruction, 0b1000_0000 | 11);
+        Ok(())
+    }
+
+    #[test]
+    fn advanced_operations_rejects_invalid_speed() {
+        assert_eq!(
+            AdvancedOperations::builder().speed(127).err(),
+            Some(Error::InvalidSpeed)
+        );
+    }
+
+    #[test]
+    fn serialize_function_group_1() -> Result<()> {
+        let pkt = FunctionGroup1::builder()
+            .address(AddressMode::Short(3))?
+            .function(0, true)?
+            .build();
+        let mut buf = SerializeBuffer::default();
+        let len = pkt.serialize(&mut buf)?;
+        assert_eq!(len, 43);
+        Ok(())
+    }
+}
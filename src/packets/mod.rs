@@ -5,18 +5,71 @@
 //! Modules containing packet definitions
 
 pub mod baseline;
+pub mod decode;
 pub mod extended;
 pub mod service_mode;
 
 pub use baseline::*;
+pub use decode::*;
 pub use service_mode::*;
 
+/// Derives a `serialize` method from fields annotated `#[dcc(address)]`
+/// and `#[dcc(bits = N)]`, for defining vendor-specific packets without
+/// hand-rolling the byte assembly. See the `dcc-derive` crate.
+#[cfg(feature = "derive")]
+pub use dcc_derive::Packet;
+
 use crate::Error;
 use bitvec::prelude::*;
 
 /// An ID corresponding to the address of a decoder
 pub type Address = u8;
 
+/// The lowest long/extended address a decoder can be assigned.
+pub const LONG_ADDRESS_MIN: u16 = 128;
+/// The highest long/extended address a decoder can be assigned.
+pub const LONG_ADDRESS_MAX: u16 = 10239;
+
+/// The addressing mode a packet targets a decoder with: either a short,
+/// single-byte address, or a 14-bit long/extended address serialized as a
+/// two-byte form.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum AddressMode {
+    /// A short address, valid from 1 to 127.
+    Short(Address),
+    /// A long/extended address, valid from [`LONG_ADDRESS_MIN`] to
+    /// [`LONG_ADDRESS_MAX`].
+    Long(u16),
+}
+
+impl AddressMode {
+    /// Returns [`Error::InvalidAddress`] if `self` is out of range for its
+    /// mode.
+    pub(crate) fn validate(self) -> Result<Self> {
+        match self {
+            Self::Short(0) | Self::Short(0x80..=u8::MAX) => Err(Error::InvalidAddress),
+            Self::Long(addr) if !(LONG_ADDRESS_MIN..=LONG_ADDRESS_MAX).contains(&addr) => {
+                Err(Error::InvalidAddress)
+            }
+            mode => Ok(mode),
+        }
+    }
+
+    /// Returns the serialized address bytes and how many of them are
+    /// meaningful: one byte for [`Self::Short`], two for [`Self::Long`]
+    /// (high byte first, carrying the `11AAAAAA` marker).
+    pub(crate) fn to_bytes(self) -> ([u8; 2], usize) {
+        match self {
+            Self::Short(addr) => ([addr, 0], 1),
+            Self::Long(addr) => (
+                [0xc0 | (addr >> 8) as u8, (addr & 0xff) as u8],
+                2,
+            ),
+        }
+    }
+}
+
 /// Represents a generic Operations Mode packet that can be transmitted or received
 pub trait Packet {
     /// Serialize the packet
@@ -48,14 +101,49 @@ pub trait Packet {
     }
 }
 
+/// Builds the data array for an addressed packet (address bytes,
+/// instruction byte(s), trailing XOR error-detection byte) and hands it
+/// to [`Packet::serialize`].
+///
+/// Exposed publicly so that code generated by `#[derive(Packet)]` (see
+/// the `dcc-derive` crate) can assemble vendor-specific packets without
+/// reaching into this module's internals.
+pub fn serialize_addressed<P: Packet>(
+    address: AddressMode,
+    instruction: &[u8],
+    buf: &mut SerializeBuffer,
+) -> Result<usize> {
+    let (address_bytes, address_len) = address.to_bytes();
+
+    // address bytes + instruction bytes + the trailing ECC byte, which is
+    // how many bytes a [`AddressMode::Long`] address leaves for
+    // `instruction` versus a [`AddressMode::Short`] one, so this can only
+    // be checked once `address` is known at runtime
+    if address_len + instruction.len() + 1 > 6 {
+        return Err(Error::TooLong);
+    }
+
+    let mut data = [0u8; 6];
+    data[..address_len].copy_from_slice(&address_bytes[..address_len]);
+    data[address_len..address_len + instruction.len()].copy_from_slice(instruction);
+    let ecc = data[..address_len + instruction.len()]
+        .iter()
+        .fold(0, |acc, b| acc ^ b);
+    data[address_len + instruction.len()] = ecc;
+
+    P::serialize(&data[..address_len + instruction.len() + 1], buf)
+}
+
 /// Convenient Result wrapper
 pub type Result<T> = core::result::Result<T, Error>;
 
 // TODO: Controller preambles must send a minimum of 14 bits whereas receivers only require 10
 struct Preamble(BitArr!(for 14, in u8, Msb0));
 
-// The size of our buffer, which should be long enough to serialize any common DCC packet into
-const MAX_BITS: usize = 15 + 4 * 9 + 1;
+// The size of our buffer, which should be long enough to serialize any common DCC packet into.
+// The current worst case is a long-address (2 byte) Operations-Mode CV access packet: 2 address
+// bytes + 3 instruction bytes + 1 error-detection byte = 6 data bytes.
+const MAX_BITS: usize = 15 + 6 * 9 + 1;
 
 /// A buffer that is [`MAX_BITS`] long, which is enough to serialize any common DCC packet info
 pub type SerializeBuffer = BitArr!(for MAX_BITS, in u8, Msb0);
@@ -79,4 +167,21 @@ mod test {
         }
         println!("Stop bit: {}", buf[offset] as u8);
     }
+
+    struct Dummy;
+    impl Packet for Dummy {}
+
+    #[test]
+    fn serialize_addressed_rejects_oversized_instruction() {
+        let mut buf = SerializeBuffer::default();
+        // a long address only leaves room for a 3-byte instruction
+        assert_eq!(
+            serialize_addressed::<Dummy>(AddressMode::Long(300), &[0, 0, 0, 0], &mut buf).err(),
+            Some(Error::TooLong)
+        );
+        // the same instruction fits behind a short address
+        assert!(
+            serialize_addressed::<Dummy>(AddressMode::Short(3), &[0, 0, 0, 0], &mut buf).is_ok()
+        );
+    }
 }
@@ -0,0 +1,208 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Packets that read or write a decoder's Configuration Variables (CVs).
+//!
+//! [`CvAccess`] uses the long-form CV access instruction, which is
+//! addressed at a specific decoder and can therefore be sent to a loco
+//! sitting on a powered layout ("Programming on the Main" / Operations
+//! Mode Programming), rather than requiring it to be isolated on a
+//! dedicated programming track.
+
+use super::{serialize_addressed, AddressMode, Packet, Result, SerializeBuffer};
+use crate::Error;
+#[cfg(test)]
+use bitvec::prelude::*;
+
+/// The lowest Configuration Variable number a decoder can expose.
+pub const CV_MIN: u16 = 1;
+/// The highest Configuration Variable number a decoder can expose.
+pub const CV_MAX: u16 = 1024;
+
+/// The CV access sub-mode carried in the `CC` bits of the long-form
+/// instruction byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+enum Mode {
+    WriteByte(u8),
+    VerifyByte(u8),
+    Bit { write: bool, bit: u8, value: bool },
+}
+
+/// Reads or writes a single Configuration Variable on a specific
+/// decoder, via the long-form CV access instruction (`1110 CCVV`
+/// followed by the CV address low byte and a data byte).
+pub struct CvAccess {
+    address: AddressMode,
+    cv: u16,
+    mode: Mode,
+}
+
+impl Packet for CvAccess {}
+
+impl CvAccess {
+    /// Builder interface for `CvAccess`.
+    pub fn builder() -> CvAccessBuilder {
+        CvAccessBuilder::default()
+    }
+
+    /// Serialize the packet into the provided buffer
+    pub fn serialize(&self, buf: &mut SerializeBuffer) -> Result<usize> {
+        let cv_address = self.cv - 1; // CVs are numbered 1-1024 on the rails as 0-1023
+
+        let cc = match self.mode {
+            Mode::VerifyByte(_) => 0b01,
+            Mode::Bit { .. } => 0b10,
+            Mode::WriteByte(_) => 0b11,
+        };
+        let instruction1 = 0b1110_0000 | (cc << 2) | (cv_address >> 8) as u8;
+        let instruction2 = (cv_address & 0xff) as u8;
+        let instruction3 = match self.mode {
+            Mode::VerifyByte(data) | Mode::WriteByte(data) => data,
+            Mode::Bit { write, bit, value } => {
+                0b1110_0000 | (write as u8) << 4 | (value as u8) << 3 | bit
+            }
+        };
+
+        serialize_addressed::<Self>(
+            self.address,
+            &[instruction1, instruction2, instruction3],
+            buf,
+        )
+    }
+}
+
+/// Builder used to construct a [`CvAccess`] packet
+#[derive(Default)]
+pub struct CvAccessBuilder {
+    address: Option<AddressMode>,
+    cv: Option<u16>,
+}
+
+impl CvAccessBuilder {
+    /// Sets the address of the decoder to reconfigure.
+    pub fn address(&mut self, address: AddressMode) -> Result<&mut Self> {
+        self.address = Some(address.validate()?);
+        Ok(self)
+    }
+
+    /// Sets the Configuration Variable number, from [`CV_MIN`] to
+    /// [`CV_MAX`]. Returns [`Error::InvalidCv`] if `cv` is outside of
+    /// this range.
+    pub fn cv(&mut self, cv: u16) -> Result<&mut Self> {
+        if !(CV_MIN..=CV_MAX).contains(&cv) {
+            Err(Error::InvalidCv)
+        } else {
+            self.cv = Some(cv);
+            Ok(self)
+        }
+    }
+
+    /// Builds a packet that writes `data` into the configured CV.
+    pub fn write_byte(&mut self, data: u8) -> Result<CvAccess> {
+        self.build(Mode::WriteByte(data))
+    }
+
+    /// Builds a packet that verifies the configured CV currently holds
+    /// `data`.
+    pub fn verify_byte(&mut self, data: u8) -> Result<CvAccess> {
+        self.build(Mode::VerifyByte(data))
+    }
+
+    /// Builds a packet that writes a single `bit` (0-7) of the configured
+    /// CV to `value`. Returns [`Error::InvalidFunction`] if `bit` is
+    /// outside of this range.
+    pub fn write_bit(&mut self, bit: u8, value: bool) -> Result<CvAccess> {
+        self.build(Mode::Bit {
+            write: true,
+            bit: validate_bit(bit)?,
+            value,
+        })
+    }
+
+    /// Builds a packet that verifies a single `bit` (0-7) of the
+    /// configured CV currently holds `value`. Returns
+    /// [`Error::InvalidFunction`] if `bit` is outside of this range.
+    pub fn verify_bit(&mut self, bit: u8, value: bool) -> Result<CvAccess> {
+        self.build(Mode::Bit {
+            write: false,
+            bit: validate_bit(bit)?,
+            value,
+        })
+    }
+
+    /// Falls back to `address = 3`. A CV must have been set with
+    /// [`Self::cv`]; returns [`Error::InvalidCv`] otherwise.
+    fn build(&mut self, mode: Mode) -> Result<CvAccess> {
+        Ok(CvAccess {
+            address: self.address.unwrap_or(AddressMode::Short(3)),
+            cv: self.cv.ok_or(Error::InvalidCv)?,
+            mode,
+        })
+    }
+}
+
+fn validate_bit(bit: u8) -> Result<u8> {
+    if bit > 7 {
+        Err(Error::InvalidFunction)
+    } else {
+        Ok(bit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_byte_instruction_layout() -> Result<()> {
+        let pkt = CvAccess::builder().cv(29)?.write_byte(0x06)?;
+        let mut buf = SerializeBuffer::default();
+        let len = pkt.serialize(&mut buf)?;
+
+        // address(1) + instr1/instr2/instr3(3) + ecc(1) = 5 data bytes
+        let expected_arr = [0xff_u8, 0xfe, 0x03, 0x76, 0x07, 0x00, 0xcf, 0x58];
+        let mut expected = SerializeBuffer::default();
+        expected[..61].copy_from_bitslice(&expected_arr.view_bits::<Msb0>()[..61]);
+        assert_eq!(len, 15 + 5 * 9 + 1);
+        assert_eq!(buf[..len], expected[..61]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_byte_long_address_serializes_to_completion() -> Result<()> {
+        // the worst case this module can produce: a long address plus the
+        // full three-byte CV access instruction (6 data bytes)
+        let pkt = CvAccess::builder()
+            .address(AddressMode::Long(300))?
+            .cv(29)?
+            .write_byte(0x06)?;
+        let mut buf = SerializeBuffer::default();
+        let len = pkt.serialize(&mut buf)?;
+        assert_eq!(len, 15 + 6 * 9 + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn cv_out_of_range_is_rejected() {
+        assert_eq!(CvAccess::builder().cv(0).err(), Some(Error::InvalidCv));
+        assert_eq!(CvAccess::builder().cv(1025).err(), Some(Error::InvalidCv));
+    }
+
+    #[test]
+    fn build_without_cv_is_rejected() {
+        assert_eq!(
+            CvAccess::builder().write_byte(1).err(),
+            Some(Error::InvalidCv)
+        );
+    }
+
+    #[test]
+    fn bit_out_of_range_is_rejected() {
+        assert_eq!(
+            CvAccess::builder().cv(29).unwrap().write_bit(8, true).err(),
+            Some(Error::InvalidFunction)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Drives a track-side H-bridge from a serialized [`SerializeBuffer`],
+//! generating the NMRA bi-phase waveform over an `embedded-hal`
+//! [`OutputPin`].
+//!
+//! A `1` bit is two symmetric ~58µs half-periods; a `0` bit is two
+//! symmetric ~100µs half-periods (stretched further for service-mode
+//! timing, see [`Transmitter::with_half_periods`]). Use
+//! [`Transmitter::transmit_blocking`] when a `DelayNs` source is on hand,
+//! or pump [`Symbols::next_half_bit`] from a timer interrupt when it
+//! isn't.
+
+use crate::packets::SerializeBuffer;
+use core::time::Duration;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+
+/// The nominal half-period of a `1` bit, per the NMRA bi-phase encoding.
+pub const ONE_HALF_PERIOD: Duration = Duration::from_micros(58);
+/// The nominal half-period of a `0` bit, per the NMRA bi-phase encoding.
+pub const ZERO_HALF_PERIOD: Duration = Duration::from_micros(100);
+
+/// Turns a serialized [`SerializeBuffer`] into an NMRA-conformant DCC
+/// waveform on an `embedded-hal` [`OutputPin`].
+///
+/// `Transmitter` only owns the pin and the half-period timing; it does
+/// not own a delay or timer source, so the same transmitter can be driven
+/// either blockingly (via [`Transmitter::transmit_blocking`]) or from an
+/// interrupt (via [`Transmitter::symbols`]).
+pub struct Transmitter<Pin> {
+    pin: Pin,
+    one_half_period: Duration,
+    zero_half_period: Duration,
+}
+
+impl<Pin: OutputPin> Transmitter<Pin> {
+    /// Creates a transmitter using the nominal [`ONE_HALF_PERIOD`] and
+    /// [`ZERO_HALF_PERIOD`] timing.
+    pub fn new(pin: Pin) -> Self {
+        Self::with_half_periods(pin, ONE_HALF_PERIOD, ZERO_HALF_PERIOD)
+    }
+
+    /// Creates a transmitter with custom half-period timing, e.g. the
+    /// stretched zero-bit used by some decoders' service-mode
+    /// acknowledgment detection.
+    pub fn with_half_periods(
+        pin: Pin,
+        one_half_period: Duration,
+        zero_half_period: Duration,
+    ) -> Self {
+        Self {
+            pin,
+            one_half_period,
+            zero_half_period,
+        }
+    }
+
+    /// Blocks until `bits` bits of `buf` have been transmitted, using
+    /// `delay` to wait out each half-period.
+    pub fn transmit_blocking<D: DelayNs>(&mut self, delay: &mut D, buf: &SerializeBuffer, bits: usize) {
+        let mut symbols = self.symbols(buf, bits);
+        while let Some(half_period) = symbols.next_half_bit() {
+            delay.delay_us(half_period.as_micros() as u32);
+        }
+    }
+
+    /// Returns a pull-based state machine that toggles the pin by one
+    /// waveform half-period per call to [`Symbols::next_half_bit`],
+    /// for callers that pump the waveform from a timer interrupt instead
+    /// of blocking.
+    pub fn symbols<'t, 'buf>(&'t mut self, buf: &'buf SerializeBuffer, bits: usize) -> Symbols<'t, 'buf, Pin> {
+        Symbols {
+            transmitter: self,
+            buf,
+            bits,
+            pos: 0,
+            second_half: false,
+        }
+    }
+}
+
+/// A "fill next symbol" state machine over a [`SerializeBuffer`], produced
+/// by [`Transmitter::symbols`].
+pub struct Symbols<'t, 'buf, Pin> {
+    transmitter: &'t mut Transmitter<Pin>,
+    buf: &'buf SerializeBuffer,
+    bits: usize,
+    pos: usize,
+    second_half: bool,
+}
+
+impl<'t, 'buf, Pin: OutputPin> Symbols<'t, 'buf, Pin> {
+    /// Toggles the pin for the next waveform half-period and returns how
+    /// long the caller should hold it there before calling again, or
+    /// `None` once all `bits` bits have been transmitted.
+    pub fn next_half_bit(&mut self) -> Option<Duration> {
+        if self.pos >= self.bits {
+            return None;
+        }
+
+        let one = self.buf[self.pos];
+        let half_period = if one {
+            self.transmitter.one_half_period
+        } else {
+            self.transmitter.zero_half_period
+        };
+
+        // the first half-period of a bit drives the pin high, the second
+        // drives it low; only the second half-period advances to the
+        // next bit
+        if self.second_half {
+            let _ = self.transmitter.pin.set_low();
+            self.second_half = false;
+            self.pos += 1;
+        } else {
+            let _ = self.transmitter.pin.set_high();
+            self.second_half = true;
+        }
+
+        Some(half_period)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal::digital::{Error, ErrorKind, ErrorType, PinState};
+
+    /// A mock pin that records every level it's driven to, so tests can
+    /// assert on the toggle sequence without real hardware.
+    #[derive(Default)]
+    struct MockPin {
+        levels: Vec<PinState>,
+    }
+
+    #[derive(Debug)]
+    struct MockPinError;
+
+    impl Error for MockPinError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    impl ErrorType for MockPin {
+        type Error = MockPinError;
+    }
+
+    impl OutputPin for MockPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.levels.push(PinState::Low);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.levels.push(PinState::High);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn next_half_bit_toggles_high_then_low_per_bit() {
+        let mut buf = SerializeBuffer::default();
+        buf.set(0, true); // a `1` bit
+        buf.set(1, false); // a `0` bit
+
+        let mut transmitter = Transmitter::new(MockPin::default());
+        let mut symbols = transmitter.symbols(&buf, 2);
+
+        assert_eq!(symbols.next_half_bit(), Some(ONE_HALF_PERIOD));
+        assert_eq!(symbols.next_half_bit(), Some(ONE_HALF_PERIOD));
+        assert_eq!(symbols.next_half_bit(), Some(ZERO_HALF_PERIOD));
+        assert_eq!(symbols.next_half_bit(), Some(ZERO_HALF_PERIOD));
+        assert_eq!(symbols.next_half_bit(), None);
+
+        assert_eq!(
+            transmitter.pin.levels,
+            vec![
+                PinState::High,
+                PinState::Low,
+                PinState::High,
+                PinState::Low,
+            ]
+        );
+    }
+
+    #[test]
+    fn next_half_bit_stops_after_requested_bit_count() {
+        let buf = SerializeBuffer::default();
+        let mut transmitter = Transmitter::new(MockPin::default());
+        let mut symbols = transmitter.symbols(&buf, 1);
+
+        assert!(symbols.next_half_bit().is_some());
+        assert!(symbols.next_half_bit().is_some());
+        assert_eq!(symbols.next_half_bit(), None);
+    }
+}